@@ -0,0 +1,352 @@
+//! Unified diffs and machine-readable mismatch reports for `--emit diff|json`.
+
+/// A single line-level edit between the original and formatted source.
+enum Edit<'a> {
+    Keep(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+/// A region of `original` that doesn't match the corresponding region of `expected`.
+pub struct Mismatch {
+    pub original_begin_line: usize,
+    pub original_end_line: usize,
+    pub expected: String,
+    pub original: String,
+}
+
+/// Render `{ name, mismatches }` entries as a JSON array, in the shape consumed by `--emit json`.
+pub fn mismatches_to_json(entries: &[(String, Vec<Mismatch>)]) -> String {
+    let entries: Vec<String> = entries
+        .iter()
+        .map(|(name, mismatches)| {
+            let mismatches: Vec<String> = mismatches
+                .iter()
+                .map(|m| {
+                    format!(
+                        "{{\"original_begin_line\":{},\"original_end_line\":{},\"expected\":{},\"original\":{}}}",
+                        m.original_begin_line,
+                        m.original_end_line,
+                        json_string(&m.expected),
+                        json_string(&m.original),
+                    )
+                })
+                .collect();
+            format!(
+                "{{\"name\":{},\"mismatches\":[{}]}}",
+                json_string(name),
+                mismatches.join(",")
+            )
+        })
+        .collect();
+    format!("[{}]", entries.join(","))
+}
+
+/// Escape `s` as a JSON string literal, including the surrounding quotes.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Return a unified diff between `original` and `formatted`, labeled with `name`, or an empty
+/// string if they are identical.
+pub fn unified(name: &str, original: &str, formatted: &str) -> String {
+    let a: Vec<&str> = original.lines().collect();
+    let b: Vec<&str> = formatted.lines().collect();
+    let annotated = annotate(&diff_edits(&a, &b));
+    if annotated.iter().all(|(_, _, tag, _)| *tag == ' ') {
+        return String::new();
+    }
+
+    let mut out = format!("--- {name}\n+++ {name}\n");
+    for range in hunk_ranges(&annotated, 3) {
+        render_hunk(&mut out, &annotated[range]);
+    }
+    out
+}
+
+/// Return the contiguous regions where `original` and `formatted` disagree.
+pub fn mismatches(original: &str, formatted: &str) -> Vec<Mismatch> {
+    let a: Vec<&str> = original.lines().collect();
+    let b: Vec<&str> = formatted.lines().collect();
+    let annotated = annotate(&diff_edits(&a, &b));
+
+    hunk_ranges(&annotated, 0)
+        .into_iter()
+        .map(|range| {
+            let region = &annotated[range];
+            let original_lines: Vec<&str> = region
+                .iter()
+                .filter(|(_, _, tag, _)| *tag != '+')
+                .map(|(_, _, _, line)| *line)
+                .collect();
+            let expected_lines: Vec<&str> = region
+                .iter()
+                .filter(|(_, _, tag, _)| *tag != '-')
+                .map(|(_, _, _, line)| *line)
+                .collect();
+            let original_begin_line = region
+                .iter()
+                .find(|(_, _, tag, _)| *tag != '+')
+                .map_or(region[0].0, |(a_line, ..)| *a_line);
+            let original_end_line = original_begin_line + original_lines.len().saturating_sub(1);
+            Mismatch {
+                original_begin_line,
+                original_end_line,
+                expected: expected_lines.join("\n"),
+                original: original_lines.join("\n"),
+            }
+        })
+        .collect()
+}
+
+/// A diagnostic line annotated with its line number in the original (`a_line`) and formatted
+/// (`b_line`) source, and a tag: `' '` (unchanged), `'-'` (removed) or `'+'` (added).
+type Annotated<'a> = (usize, usize, char, &'a str);
+
+fn annotate<'a>(edits: &[Edit<'a>]) -> Vec<Annotated<'a>> {
+    let mut out = Vec::with_capacity(edits.len());
+    let (mut a_line, mut b_line) = (1usize, 1usize);
+    for edit in edits {
+        match edit {
+            Edit::Keep(line) => {
+                out.push((a_line, b_line, ' ', *line));
+                a_line += 1;
+                b_line += 1;
+            }
+            Edit::Delete(line) => {
+                out.push((a_line, b_line, '-', *line));
+                a_line += 1;
+            }
+            Edit::Insert(line) => {
+                out.push((a_line, b_line, '+', *line));
+                b_line += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Group changed lines into ranges, each padded with up to `context` unchanged lines on either
+/// side, merging ranges that end up overlapping or adjacent.
+fn hunk_ranges(annotated: &[Annotated], context: usize) -> Vec<std::ops::Range<usize>> {
+    let mut ranges: Vec<std::ops::Range<usize>> = Vec::new();
+    for (i, (_, _, tag, _)) in annotated.iter().enumerate() {
+        if *tag == ' ' {
+            continue;
+        }
+        let lo = i.saturating_sub(context);
+        let hi = (i + context + 1).min(annotated.len());
+        match ranges.last_mut() {
+            Some(last) if lo <= last.end => last.end = last.end.max(hi),
+            _ => ranges.push(lo..hi),
+        }
+    }
+    ranges
+}
+
+/// Render one unified-diff hunk (header plus `' '`/`-`/`+` lines) for `region` into `out`.
+fn render_hunk(out: &mut String, region: &[Annotated]) {
+    let orig_start = region
+        .iter()
+        .find(|(_, _, tag, _)| *tag != '+')
+        .map_or(region[0].0, |(l, ..)| *l);
+    let new_start = region
+        .iter()
+        .find(|(_, _, tag, _)| *tag != '-')
+        .map_or(region[0].1, |(_, l, ..)| *l);
+    let orig_len = region.iter().filter(|(_, _, tag, _)| *tag != '+').count();
+    let new_len = region.iter().filter(|(_, _, tag, _)| *tag != '-').count();
+
+    out.push_str(&format!(
+        "@@ -{orig_start},{orig_len} +{new_start},{new_len} @@\n"
+    ));
+    for (_, _, tag, line) in region {
+        out.push(*tag);
+        out.push_str(line);
+        out.push('\n');
+    }
+}
+
+/// Above this many (trimmed-original-lines × trimmed-formatted-lines) cells, the O(n·m) LCS
+/// table below would be too slow/memory-hungry to run; fall back to treating the whole
+/// remaining core as replaced rather than hanging or exhausting memory on a huge rewrite.
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+/// Compute a line-level edit script between `a` and `b`.
+///
+/// mofmt diffs are almost always a small changed region inside an otherwise-unchanged file, so
+/// the common prefix and suffix are trimmed off first and matched as `Keep` directly, which
+/// keeps the O(n·m) LCS below fast in practice even on large files.
+fn diff_edits<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<Edit<'a>> {
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let core = if a_mid.len().saturating_mul(b_mid.len()) > MAX_LCS_CELLS {
+        a_mid
+            .iter()
+            .map(|l| Edit::Delete(l))
+            .chain(b_mid.iter().map(|l| Edit::Insert(l)))
+            .collect()
+    } else {
+        lcs_edits(a_mid, b_mid)
+    };
+
+    let mut edits = Vec::with_capacity(prefix + core.len() + suffix);
+    edits.extend(a[..prefix].iter().map(|l| Edit::Keep(l)));
+    edits.extend(core);
+    edits.extend(a[a.len() - suffix..].iter().map(|l| Edit::Keep(l)));
+    edits
+}
+
+/// Compute a line-level edit script between `a` and `b` via a classic LCS dynamic program.
+fn lcs_edits<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<Edit<'a>> {
+    let (n, m) = (a.len(), b.len());
+    let mut dp = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            dp[i][j] = if a[i] == b[j] {
+                dp[i + 1][j + 1] + 1
+            } else {
+                dp[i + 1][j].max(dp[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            edits.push(Edit::Keep(a[i]));
+            i += 1;
+            j += 1;
+        } else if dp[i + 1][j] >= dp[i][j + 1] {
+            edits.push(Edit::Delete(a[i]));
+            i += 1;
+        } else {
+            edits.push(Edit::Insert(b[j]));
+            j += 1;
+        }
+    }
+    edits.extend(a[i..].iter().map(|l| Edit::Delete(l)));
+    edits.extend(b[j..].iter().map(|l| Edit::Insert(l)));
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit_tags(a: &str, b: &str) -> Vec<char> {
+        let (a, b): (Vec<&str>, Vec<&str>) = (a.lines().collect(), b.lines().collect());
+        annotate(&diff_edits(&a, &b))
+            .iter()
+            .map(|(_, _, tag, _)| *tag)
+            .collect()
+    }
+
+    #[test]
+    fn identical_input_has_no_changed_tags() {
+        let source = "a\nb\nc\n";
+        assert!(edit_tags(source, source).iter().all(|tag| *tag == ' '));
+    }
+
+    #[test]
+    fn change_in_the_middle_keeps_prefix_and_suffix() {
+        let tags = edit_tags("a\nb\nc\nd\n", "a\nx\nc\nd\n");
+        assert_eq!(tags, vec![' ', '-', '+', ' ', ' ']);
+    }
+
+    #[test]
+    fn diff_edits_matches_lcs_edits_on_small_input() {
+        let a: Vec<&str> = "a\nb\nc\n".lines().collect();
+        let b: Vec<&str> = "a\nx\nc\n".lines().collect();
+        let via_diff_edits: Vec<char> = annotate(&diff_edits(&a, &b))
+            .iter()
+            .map(|(_, _, tag, _)| *tag)
+            .collect();
+        let via_lcs_edits: Vec<char> = annotate(&lcs_edits(&a, &b))
+            .iter()
+            .map(|(_, _, tag, _)| *tag)
+            .collect();
+        assert_eq!(via_diff_edits, via_lcs_edits);
+    }
+
+    #[test]
+    fn oversized_core_falls_back_to_delete_insert_instead_of_lcs() {
+        let a: Vec<String> = (0..2500).map(|i| format!("a{i}")).collect();
+        let b: Vec<String> = (0..2500).map(|i| format!("b{i}")).collect();
+        let (a, b): (Vec<&str>, Vec<&str>) = (
+            a.iter().map(String::as_str).collect(),
+            b.iter().map(String::as_str).collect(),
+        );
+        assert!(a.len() * b.len() > MAX_LCS_CELLS);
+
+        let edits = diff_edits(&a, &b);
+        assert!(edits.iter().all(|e| !matches!(e, Edit::Keep(_))));
+        assert_eq!(
+            edits
+                .iter()
+                .filter(|e| matches!(e, Edit::Delete(_)))
+                .count(),
+            a.len()
+        );
+        assert_eq!(
+            edits
+                .iter()
+                .filter(|e| matches!(e, Edit::Insert(_)))
+                .count(),
+            b.len()
+        );
+    }
+
+    #[test]
+    fn unified_diff_is_empty_for_identical_input() {
+        let source = "a\nb\nc\n";
+        assert_eq!(unified("f.mo", source, source), "");
+    }
+
+    #[test]
+    fn unified_diff_reports_original_and_formatted_line_numbers() {
+        let diff = unified("f.mo", "a\nb\nc\n", "a\nx\nc\n");
+        assert!(diff.contains("--- f.mo"));
+        assert!(diff.contains("@@ -1,3 +1,3 @@"));
+        assert!(diff.contains("-b"));
+        assert!(diff.contains("+x"));
+    }
+
+    #[test]
+    fn mismatches_reports_original_line_range() {
+        let m = mismatches("a\nb\nc\n", "a\nx\nc\n");
+        assert_eq!(m.len(), 1);
+        assert_eq!(m[0].original_begin_line, 2);
+        assert_eq!(m[0].original_end_line, 2);
+        assert_eq!(m[0].original, "b");
+        assert_eq!(m[0].expected, "x");
+    }
+
+    #[test]
+    fn mismatches_is_empty_for_identical_input() {
+        let source = "a\nb\nc\n";
+        assert!(mismatches(source, source).is_empty());
+    }
+}
@@ -1,18 +1,30 @@
+mod config;
 mod formatting;
 mod printing;
+mod skip;
+
+pub use config::{Config, NewlineStyle};
 
 use crate::parser::ModelicaCST;
 
 impl ModelicaCST {
-    /// Return string containing formatted Modelica code represented by the CST.
-    pub fn pretty_print(&self) -> String {
-        let markers = formatting::format(self, None);
-        printing::print(self, markers)
-    }
+    /// Return string containing formatted Modelica code represented by the CST, using `config`.
+    ///
+    /// Any `// mofmt::off` / `// mofmt::on` / `// mofmt::skip` guarded region has its textual
+    /// content (tokens, spacing, indentation) spliced back in from the original source,
+    /// overriding whatever `formatting`/`printing` produced for it. The output's line-ending
+    /// style still comes uniformly from `config.newline_style`, like the rest of the file — a
+    /// guarded region does not keep its own original line endings if they differ from the rest.
+    pub fn pretty_print(&self, config: &Config) -> String {
+        let markers = formatting::format(self, config);
+        let formatted = printing::print(self, markers);
 
-    /// Return string containing formatted Modelica code with specified max line length.
-    pub fn pretty_print_with_line_length(&self, max_line_length: usize) -> String {
-        let markers = formatting::format(self, Some(max_line_length));
-        printing::print(self, markers)
+        let original = self.tokens().code().to_string();
+        let verbatim = skip::verbatim_ranges(&original);
+        if verbatim.is_empty() {
+            formatted
+        } else {
+            skip::restore(&original, &formatted, &verbatim)
+        }
     }
 }
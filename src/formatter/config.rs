@@ -0,0 +1,206 @@
+use serde::Deserialize;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Formatting options, merged from a `mofmt.toml` file and CLI overrides.
+///
+/// `ModelicaCST::pretty_print` takes a `Config` rather than one argument per
+/// option, so new options don't each need their own `pretty_print_with_*`
+/// method.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub line_length: Option<usize>,
+    pub newline_style: Option<NewlineStyle>,
+}
+
+impl Config {
+    /// Merge `self` with `overrides`, with `overrides` taking precedence wherever it sets a field.
+    pub fn merge(self, overrides: Config) -> Config {
+        Config {
+            line_length: overrides.line_length.or(self.line_length),
+            newline_style: overrides.newline_style.or(self.newline_style),
+        }
+    }
+
+    /// Walk upward from `start` looking for a `mofmt.toml`, returning the config parsed from the
+    /// nearest one found, or the default config if none exists.
+    ///
+    /// `start` is resolved against the current directory first, so a relative path (e.g. the
+    /// common `mofmt some/file.mo` invocation) still walks up the real filesystem tree above the
+    /// current directory rather than stopping at it.
+    pub fn discover(start: &Path) -> Result<Config, String> {
+        let start = start
+            .canonicalize()
+            .unwrap_or_else(|_| env::current_dir().unwrap_or_default().join(start));
+        let mut dir: Option<PathBuf> = if start.is_dir() {
+            Some(start)
+        } else {
+            start.parent().map(Path::to_path_buf)
+        };
+        while let Some(d) = dir {
+            let candidate = d.join("mofmt.toml");
+            if candidate.is_file() {
+                return Config::from_file(&candidate);
+            }
+            dir = d.parent().map(Path::to_path_buf);
+        }
+        Ok(Config::default())
+    }
+
+    /// Parse a `Config` from the `mofmt.toml` file at `path`.
+    fn from_file(path: &Path) -> Result<Config, String> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        toml::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::Mutex;
+
+    /// Guards tests that change the process-wide current directory, since `cargo test` runs
+    /// tests on multiple threads by default and two tests racing to set/read the cwd would flake.
+    static CWD_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Create a fresh, empty directory under the system temp dir for a test to use.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            env::temp_dir().join(format!("mofmt-config-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn merge_prefers_overrides_and_falls_back_to_base() {
+        let base = Config {
+            line_length: Some(80),
+            newline_style: Some(NewlineStyle::Unix),
+        };
+        let overrides = Config {
+            line_length: Some(120),
+            newline_style: None,
+        };
+        let merged = base.merge(overrides);
+        assert_eq!(merged.line_length, Some(120));
+        assert_eq!(merged.newline_style, Some(NewlineStyle::Unix));
+    }
+
+    #[test]
+    fn discover_finds_config_above_a_relative_subdirectory_path() {
+        let _guard = CWD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let root = temp_dir("relative-subdir");
+        let subpkg = root.join("subpkg");
+        fs::create_dir_all(&subpkg).unwrap();
+        fs::write(root.join("mofmt.toml"), "line_length = 100\n").unwrap();
+        fs::write(subpkg.join("file.mo"), "").unwrap();
+
+        let original_cwd = env::current_dir().unwrap();
+        env::set_current_dir(&subpkg).unwrap();
+        let result = Config::discover(Path::new("file.mo"));
+        env::set_current_dir(original_cwd).unwrap();
+
+        assert_eq!(result.unwrap().line_length, Some(100));
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn discover_returns_default_when_no_mofmt_toml_exists() {
+        let root = temp_dir("no-config");
+        fs::write(root.join("file.mo"), "").unwrap();
+
+        let result = Config::discover(&root.join("file.mo"));
+
+        assert_eq!(result.unwrap(), Config::default());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn newline_style_parse_round_trips_known_values() {
+        assert_eq!(NewlineStyle::parse("auto"), Some(NewlineStyle::Auto));
+        assert_eq!(NewlineStyle::parse("unix"), Some(NewlineStyle::Unix));
+        assert_eq!(NewlineStyle::parse("windows"), Some(NewlineStyle::Windows));
+        assert_eq!(NewlineStyle::parse("native"), Some(NewlineStyle::Native));
+        assert_eq!(NewlineStyle::parse("bogus"), None);
+    }
+
+    #[test]
+    fn newline_style_explicit_variants_ignore_source_content() {
+        assert_eq!(NewlineStyle::Unix.line_ending("a\r\nb\r\n"), "\n");
+        assert_eq!(NewlineStyle::Windows.line_ending("a\nb\n"), "\r\n");
+    }
+
+    #[test]
+    fn newline_style_auto_picks_the_dominant_ending() {
+        assert_eq!(NewlineStyle::Auto.line_ending("a\r\nb\r\nc\n"), "\r\n");
+        assert_eq!(NewlineStyle::Auto.line_ending("a\nb\nc\r\n"), "\n");
+    }
+
+    #[test]
+    fn newline_style_auto_breaks_ties_towards_unix() {
+        assert_eq!(NewlineStyle::Auto.line_ending("a\r\nb\n"), "\n");
+        assert_eq!(NewlineStyle::Auto.line_ending("no newlines here"), "\n");
+    }
+}
+
+/// Line ending to use for formatted output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NewlineStyle {
+    /// Preserve whichever line ending is already dominant in each input file.
+    Auto,
+    /// Always use `\n`.
+    Unix,
+    /// Always use `\r\n`.
+    Windows,
+    /// Use the platform's usual line ending (`\r\n` on Windows, `\n` elsewhere).
+    Native,
+}
+
+impl Default for NewlineStyle {
+    fn default() -> Self {
+        NewlineStyle::Auto
+    }
+}
+
+impl NewlineStyle {
+    /// Parse a `NewlineStyle` from a `--newline-style` CLI argument.
+    pub fn parse(s: &str) -> Option<NewlineStyle> {
+        match s {
+            "auto" => Some(NewlineStyle::Auto),
+            "unix" => Some(NewlineStyle::Unix),
+            "windows" => Some(NewlineStyle::Windows),
+            "native" => Some(NewlineStyle::Native),
+            _ => None,
+        }
+    }
+
+    /// Resolve the line ending to use for output formatted from `source`.
+    pub fn line_ending(self, source: &str) -> &'static str {
+        match self {
+            NewlineStyle::Unix => "\n",
+            NewlineStyle::Windows => "\r\n",
+            NewlineStyle::Native => {
+                if cfg!(windows) {
+                    "\r\n"
+                } else {
+                    "\n"
+                }
+            }
+            NewlineStyle::Auto => {
+                let crlf = source.matches("\r\n").count();
+                let lf_only = source.matches('\n').count() - crlf;
+                if crlf > lf_only {
+                    "\r\n"
+                } else {
+                    "\n"
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use std::ops::Range;
+
+/// A half-open range of 0-based line indices in the original source whose textual content
+/// (tokens, spacing, indentation) must be reproduced exactly in the formatted output. The file's
+/// overall line-ending style still comes from `config.newline_style` like the rest of the file —
+/// `--newline-style` normalizes the whole output uniformly, so a guarded region does not keep its
+/// own original line-ending bytes if they differ from the rest of the file.
+pub type Verbatim = Range<usize>;
+
+const OFF: &str = "// mofmt::off";
+const ON: &str = "// mofmt::on";
+const SKIP: &str = "// mofmt::skip";
+
+/// Scan `source` for `mofmt::off` / `mofmt::on` / `mofmt::skip` directive comments and return
+/// the 0-based line ranges that must be preserved verbatim instead of reformatted.
+///
+/// A `mofmt::skip` comment preserves the single statement or expression that follows it: every
+/// line up to and including the one where bracket nesting (`()`/`[]`/`{}`) returns to zero and
+/// the line ends with `;`, or up to (but excluding) the next directive comment or end of file,
+/// whichever comes first. This covers multi-line elements (e.g. an aligned matrix literal) that
+/// may themselves contain blank lines, without absorbing unrelated code that follows.
+pub fn verbatim_ranges(source: &str) -> Vec<Verbatim> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut ranges = Vec::new();
+    let mut off_start: Option<usize> = None;
+    let mut i = 0;
+    while i < lines.len() {
+        let trimmed = lines[i].trim();
+        if let Some(start) = off_start {
+            if trimmed == ON {
+                ranges.push(start..i);
+                off_start = None;
+            }
+            i += 1;
+            continue;
+        }
+        match trimmed {
+            OFF => off_start = Some(i + 1),
+            SKIP => {
+                let start = i + 1;
+                let end = element_end(&lines, start);
+                if end > start {
+                    ranges.push(start..end);
+                }
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    ranges
+}
+
+/// Return the index just past the statement/expression that starts at `lines[start]`: the first
+/// line at or after `start` where bracket nesting has returned to zero and the line ends with
+/// `;`, or the line before the next directive comment, or `lines.len()` at end of file.
+fn element_end(lines: &[&str], start: usize) -> usize {
+    let mut depth: i32 = 0;
+    let mut i = start;
+    while i < lines.len() {
+        let trimmed = lines[i].trim();
+        if trimmed == OFF || trimmed == ON || trimmed == SKIP {
+            break;
+        }
+        for c in lines[i].chars() {
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        i += 1;
+        if depth <= 0 && trimmed.ends_with(';') {
+            break;
+        }
+    }
+    i
+}
+
+/// Reconstruct `formatted` so that each original line range in `ranges` is spliced back in
+/// verbatim.
+///
+/// Each range is located in `formatted` via `keep_alignment`, an LCS-based line alignment between
+/// `original` and `formatted`, rather than by searching for a single bordering line's text: text
+/// search can silently match the wrong occurrence when that text recurs elsewhere in the file
+/// (e.g. two guarded blocks bordered by the same boilerplate line), which would splice a verbatim
+/// range into the wrong place with no error. The alignment is order-preserving, so it locates
+/// each range relative to the ones before it rather than re-searching from scratch.
+pub fn restore(original: &str, formatted: &str, ranges: &[Verbatim]) -> String {
+    if ranges.is_empty() {
+        return formatted.to_string();
+    }
+
+    let orig_lines: Vec<&str> = original.lines().collect();
+    let fmt_lines: Vec<&str> = formatted.lines().collect();
+    let keep = keep_alignment(&orig_lines, &fmt_lines);
+
+    let mut out: Vec<&str> = Vec::with_capacity(fmt_lines.len());
+    let mut fmt_pos = 0;
+    let mut keep_idx = 0;
+
+    for range in ranges {
+        while keep_idx < keep.len() && keep[keep_idx].0 < range.start {
+            keep_idx += 1;
+        }
+        let splice_start = if keep_idx == 0 {
+            fmt_pos
+        } else {
+            keep[keep_idx - 1].1 + 1
+        }
+        .max(fmt_pos);
+        out.extend_from_slice(&fmt_lines[fmt_pos..splice_start.min(fmt_lines.len())]);
+        out.extend_from_slice(&orig_lines[range.clone()]);
+
+        while keep_idx < keep.len() && keep[keep_idx].0 < range.end {
+            keep_idx += 1;
+        }
+        fmt_pos = if keep_idx < keep.len() {
+            keep[keep_idx].1
+        } else {
+            fmt_lines.len()
+        }
+        .max(splice_start);
+    }
+    out.extend_from_slice(&fmt_lines[fmt_pos.min(fmt_lines.len())..]);
+
+    let mut result = out.join("\n");
+    if formatted.ends_with('\n') {
+        result.push('\n');
+    }
+    result
+}
+
+/// Align `a` and `b` by longest common subsequence, returning the `(a_index, b_index)` pairs of
+/// every line the alignment keeps in common, in increasing order of both indices.
+///
+/// As with `diff::diff_edits`, the common prefix and suffix are matched directly and only the
+/// remaining core goes through the O(n·m) LCS table, since a formatting pass changes only a small
+/// region of an otherwise-unchanged file.
+fn keep_alignment(a: &[&str], b: &[&str]) -> Vec<(usize, usize)> {
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    pairs.extend(
+        lcs_keep_pairs(a_mid, b_mid)
+            .into_iter()
+            .map(|(i, j)| (i + prefix, j + prefix)),
+    );
+    let (suffix_a, suffix_b) = (a.len() - suffix, b.len() - suffix);
+    pairs.extend((0..suffix).map(|k| (suffix_a + k, suffix_b + k)));
+    pairs
+}
+
+/// Compute the `(a_index, b_index)` pairs kept by a classic LCS dynamic program over `a` and `b`.
+fn lcs_keep_pairs(a: &[&str], b: &[&str]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len(), b.len());
+    let mut dp = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            dp[i][j] = if a[i] == b[j] {
+                dp[i + 1][j + 1] + 1
+            } else {
+                dp[i + 1][j].max(dp[i][j + 1])
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if dp[i + 1][j] >= dp[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn off_on_block_is_detected() {
+        let source = "model M\n// mofmt::off\n  1, 2;\n  3, 4;\n// mofmt::on\nend M;\n";
+        assert_eq!(verbatim_ranges(source), vec![2..4]);
+    }
+
+    #[test]
+    fn skip_covers_until_statement_terminator() {
+        let source = "model M\n// mofmt::skip\n  1, 2,\n  3, 4;\n\nend M;\n";
+        assert_eq!(verbatim_ranges(source), vec![2..4]);
+    }
+
+    #[test]
+    fn skip_stops_before_next_directive_marker() {
+        let source =
+            "model M\n// mofmt::skip\n  1,2;\n// mofmt::off\n  3,4;\n// mofmt::on\nend M;\n";
+        // The skip covers only its own statement; it must not absorb the off/on block after it.
+        assert_eq!(verbatim_ranges(source), vec![2..3, 4..5]);
+    }
+
+    #[test]
+    fn skip_spans_blank_lines_inside_balanced_brackets() {
+        let source = "model M\n// mofmt::skip\n  x := [1, 2,\n\n        3, 4];\nend M;\n";
+        assert_eq!(verbatim_ranges(source), vec![2..5]);
+    }
+
+    #[test]
+    fn no_directives_means_no_verbatim_ranges() {
+        let source = "model M\n  Real x;\nend M;\n";
+        assert!(verbatim_ranges(source).is_empty());
+    }
+
+    #[test]
+    fn restore_reproduces_guarded_block_byte_for_byte() {
+        let original =
+            "model M\n// mofmt::off\n  1,   2;\n    3,4;\n// mofmt::on\n  Real x;\nend M;\n";
+        // A stand-in for what `formatting`/`printing` would otherwise do: reindent and
+        // re-align every line, including the ones that should have been left alone.
+        let formatted = "model M\n// mofmt::off\n1, 2;\n3, 4;\n// mofmt::on\nReal x;\nend M;\n";
+
+        let ranges = verbatim_ranges(original);
+        let restored = restore(original, formatted, &ranges);
+
+        assert!(restored.contains("  1,   2;\n    3,4;\n"));
+        assert_eq!(restored.lines().last(), Some("end M;"));
+    }
+
+    #[test]
+    fn restore_handles_duplicate_anchor_text_without_misplacing_blocks() {
+        // Two skip blocks are both bordered by the same boilerplate line ("end for;" and the
+        // "// mofmt::skip" marker itself), the exact ambiguity a single-anchor text search could
+        // resolve to the wrong occurrence.
+        let original = "model M\n// mofmt::skip\n  AAA,\n  BBB;\nend for;\n  middle;\n// mofmt::skip\n  CCC,\n  DDD;\nend for;\nend M;\n";
+        let formatted = "model M\n// mofmt::skip\nAAA,\nBBB;\nend for;\nmiddle;\n// mofmt::skip\nCCC,\nDDD;\nend for;\nend M;\n";
+
+        let ranges = verbatim_ranges(original);
+        let restored = restore(original, formatted, &ranges);
+
+        assert_eq!(
+            restored,
+            "model M\n// mofmt::skip\n  AAA,\n  BBB;\nend for;\nmiddle;\n// mofmt::skip\n  CCC,\n  DDD;\nend for;\nend M;\n"
+        );
+    }
+}
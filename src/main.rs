@@ -1,5 +1,9 @@
-use mofmt::{ModelicaCST, SyntaxKind};
-use std::io::{stdout, Write};
+mod diff;
+
+use diff::Mismatch;
+use mofmt::{Config, ModelicaCST, NewlineStyle, SyntaxKind};
+use rayon::prelude::*;
+use std::io::{stdin, stdout, Read, Write};
 use std::path::{Path, PathBuf};
 use std::{env, fs};
 
@@ -15,9 +19,49 @@ Options:
 -v, --version: display a version number and exit
 --check: run mofmt in check mode (without modifying the file)
 --line-length <N>: set maximum line length (disabled by default)
+--stdin: read source from standard input and write the formatted result to
+         standard output, instead of operating on PATHS
+--emit <files|stdout|diff|json>: how to report formatting results (default: files)
+    files:  write formatted output back to each file, as today
+    stdout: print formatted output to standard output instead of writing files
+    diff:   print a unified diff of the changes each file needs, writing nothing
+    json:   print a JSON array of per-file mismatches, writing nothing
+--jobs <N>: number of threads to format files with (default: all cores)
+--newline-style <auto|unix|windows|native>: line ending to use (default: auto)
+    auto:    preserve whichever line ending is already dominant in each file
+    unix:    always use \n
+    windows: always use \r\n
+    native:  use \r\n on Windows, \n elsewhere
+
+mofmt looks for a `mofmt.toml` file in each input file's directory and its
+ancestors, merging it with any options given on the command line (which
+take precedence).
 "#;
 
-const EOL: &str = if cfg!(windows) { "\r\n" } else { "\n" };
+/// How formatting results should be reported, set via `--emit`.
+#[derive(Clone, Copy, PartialEq)]
+enum Emit {
+    /// Write formatted output back to each file (the default).
+    Files,
+    /// Print formatted output to standard output instead of writing files.
+    Stdout,
+    /// Print a unified diff of the changes each file needs.
+    Diff,
+    /// Print a JSON array of per-file mismatches.
+    Json,
+}
+
+impl Emit {
+    fn parse(s: &str) -> Option<Emit> {
+        match s {
+            "files" => Some(Emit::Files),
+            "stdout" => Some(Emit::Stdout),
+            "diff" => Some(Emit::Diff),
+            "json" => Some(Emit::Json),
+            _ => None,
+        }
+    }
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -33,26 +77,81 @@ fn main() {
     } else {
         // Parse options
         let mut check = false;
-        let mut line_length = None;
+        let mut use_stdin = false;
+        let mut emit = Emit::Files;
+        let mut jobs = None;
+        let mut cli_config = Config::default();
         let mut i = 1;
 
         while i < args.len() {
             if args[i] == "--check" {
                 check = true;
                 i += 1;
+            } else if args[i] == "--stdin" {
+                use_stdin = true;
+                i += 1;
+            } else if args[i] == "--emit" {
+                if i + 1 >= args.len() {
+                    eprintln!("Missing value for --emit argument.\n{}", HELP);
+                    std::process::exit(1);
+                }
+                match Emit::parse(&args[i + 1]) {
+                    Some(e) => emit = e,
+                    None => {
+                        eprintln!("Invalid emit mode: '{}'. Expected one of files, stdout, diff, json.\n{}", args[i + 1], HELP);
+                        std::process::exit(1);
+                    }
+                }
+                i += 2;
+            } else if args[i] == "--jobs" {
+                if i + 1 >= args.len() {
+                    eprintln!("Missing value for --jobs argument.\n{}", HELP);
+                    std::process::exit(1);
+                }
+                match args[i + 1].parse::<usize>() {
+                    Ok(n) if n > 0 => jobs = Some(n),
+                    _ => {
+                        eprintln!(
+                            "Invalid jobs count: '{}'. Must be a positive integer.\n{}",
+                            args[i + 1],
+                            HELP
+                        );
+                        std::process::exit(1);
+                    }
+                }
+                i += 2;
+            } else if args[i] == "--newline-style" {
+                if i + 1 >= args.len() {
+                    eprintln!("Missing value for --newline-style argument.\n{}", HELP);
+                    std::process::exit(1);
+                }
+                match NewlineStyle::parse(&args[i + 1]) {
+                    Some(s) => cli_config.newline_style = Some(s),
+                    None => {
+                        eprintln!("Invalid newline style: '{}'. Expected one of auto, unix, windows, native.\n{}", args[i + 1], HELP);
+                        std::process::exit(1);
+                    }
+                }
+                i += 2;
             } else if args[i] == "--line-length" {
                 if i + 1 >= args.len() {
                     eprintln!("Missing value for --line-length argument.\n{}", HELP);
                     std::process::exit(1);
                 }
                 match args[i + 1].parse::<usize>() {
-                    Ok(n) if n > 0 => line_length = Some(n),
+                    Ok(n) if n > 0 => cli_config.line_length = Some(n),
                     _ => {
-                        eprintln!("Invalid line length: '{}'. Must be a positive integer.\n{}", args[i + 1], HELP);
+                        eprintln!(
+                            "Invalid line length: '{}'. Must be a positive integer.\n{}",
+                            args[i + 1],
+                            HELP
+                        );
                         std::process::exit(1);
                     }
                 }
                 i += 2;
+            } else if args[i] == "-" {
+                break;
             } else if args[i].starts_with('-') {
                 eprintln!("Unrecognized option: '{}'.\n{}", args[i], HELP);
                 std::process::exit(1);
@@ -61,20 +160,100 @@ fn main() {
             }
         }
 
-        if i >= args.len() {
+        if use_stdin || args[i..] == ["-".to_string()] {
+            format_stdin(check, emit, &cli_config);
+        } else if i >= args.len() {
             eprintln!("Missing PATHS arguments.\n{}", HELP);
             std::process::exit(1);
+        } else {
+            format_files(&args[i..], check, emit, jobs, &cli_config);
         }
+    }
+}
+
+/// Format source read from standard input and write the result to standard output.
+fn format_stdin(check: bool, emit: Emit, cli_config: &Config) {
+    let mut source = String::new();
+    if let Err(e) = stdin().read_to_string(&mut source) {
+        eprintln!("stdin: error: {}", e);
+        std::process::exit(1);
+    }
+
+    let name = "<stdin>";
+    let parsed = ModelicaCST::from(name.to_string(), source, SyntaxKind::StoredDefinition);
+    let mut errors = parsed.tokens().errors();
+    errors.append(&mut parsed.errors());
+    if !errors.is_empty() {
+        eprintln!("{}: syntax errors detected\n{}", name, errors.join("\n"));
+        std::process::exit(1);
+    }
+
+    let config = Config::discover(&env::current_dir().unwrap_or_default())
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+        .merge(cli_config.clone());
+    let original = parsed.tokens().code().to_string();
+    let output = apply_newline_style(&parsed.pretty_print(&config), &original, &config);
 
-        format_files(&args[i..], check, line_length);
+    match emit {
+        Emit::Files | Emit::Stdout => {
+            if check {
+                std::process::exit(if output != original { 1 } else { 0 });
+            }
+            print!("{}", output);
+        }
+        Emit::Diff => {
+            let d = diff::unified(name, &original, &output);
+            if !d.is_empty() {
+                print!("{}", d);
+                std::process::exit(1);
+            }
+        }
+        Emit::Json => {
+            let m = diff::mismatches(&original, &output);
+            let code = if m.is_empty() { 0 } else { 1 };
+            let entries = if m.is_empty() {
+                Vec::new()
+            } else {
+                vec![(name.to_string(), m)]
+            };
+            println!("{}", diff::mismatches_to_json(&entries));
+            std::process::exit(code);
+        }
     }
+    std::process::exit(0);
+}
+
+/// The result of formatting a single file, ready to be reported once every file has been
+/// processed.
+enum FileOutcome {
+    SyntaxErrors {
+        name: String,
+        errors: Vec<String>,
+    },
+    ReadError {
+        name: String,
+        error: String,
+    },
+    Formatted {
+        path: PathBuf,
+        name: String,
+        original: String,
+        output: String,
+    },
 }
 
 /// Format files specified in the argument list
-fn format_files(args: &[String], check: bool, line_length: Option<usize>) {
-    let mut code = 0;
+fn format_files(
+    args: &[String],
+    check: bool,
+    emit: Emit,
+    jobs: Option<usize>,
+    cli_config: &Config,
+) {
     let mut files = Vec::new();
-    let mut lock = stdout().lock();
     args.iter()
         .map(PathBuf::from)
         .map(|p| {
@@ -85,46 +264,108 @@ fn format_files(args: &[String], check: bool, line_length: Option<usize>) {
             }
         })
         .for_each(|mut v| files.append(&mut v));
-    files.iter().for_each(|p| {
-        let contents = read_file(p);
-        let name = p.display();
-        match contents {
+
+    let format_one = |p: &PathBuf| -> FileOutcome {
+        let name = p.display().to_string();
+        match read_file(p) {
+            Err(error) => FileOutcome::ReadError { name, error },
             Ok(source) => {
-                let parsed = ModelicaCST::from(name.to_string(), source, SyntaxKind::StoredDefinition);
+                let parsed = ModelicaCST::from(name.clone(), source, SyntaxKind::StoredDefinition);
                 let mut errors = parsed.tokens().errors();
                 errors.append(&mut parsed.errors());
                 if !errors.is_empty() {
-                    writeln!(
-                        lock,
-                        "\n{}: \x1b[31msyntax errors detected\x1b[0m\n{}",
-                        name,
-                        errors.join("\n")
-                    )
-                    .unwrap();
-                    code = 1;
-                } else {
-                    let output = match line_length {
-                        Some(len) => parsed.pretty_print_with_line_length(len),
-                        None => parsed.pretty_print(),
-                    } + EOL;
+                    return FileOutcome::SyntaxErrors { name, errors };
+                }
+                let config = Config::discover(p)
+                    .unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    })
+                    .merge(cli_config.clone());
+                let original = parsed.tokens().code().to_string();
+                let output = apply_newline_style(&parsed.pretty_print(&config), &original, &config);
+                FileOutcome::Formatted {
+                    path: p.clone(),
+                    name,
+                    original,
+                    output,
+                }
+            }
+        }
+    };
+
+    let outcomes: Vec<FileOutcome> = match jobs {
+        Some(jobs) => rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build()
+            .unwrap_or_else(|e| {
+                eprintln!("error building thread pool: {}", e);
+                std::process::exit(1);
+            })
+            .install(|| files.par_iter().map(format_one).collect()),
+        None => files.par_iter().map(format_one).collect(),
+    };
+
+    let mut code = 0;
+    let mut lock = stdout().lock();
+    let mut json_entries: Vec<(String, Vec<Mismatch>)> = Vec::new();
+    for outcome in outcomes {
+        match outcome {
+            FileOutcome::ReadError { name, error } => {
+                eprintln!("{}: error: {}", name, error);
+                code = 1;
+            }
+            FileOutcome::SyntaxErrors { name, errors } => {
+                writeln!(
+                    lock,
+                    "\n{}: \x1b[31msyntax errors detected\x1b[0m\n{}",
+                    name,
+                    errors.join("\n")
+                )
+                .unwrap();
+                code = 1;
+            }
+            FileOutcome::Formatted {
+                path,
+                name,
+                original,
+                output,
+            } => match emit {
+                Emit::Files => {
                     if check {
-                        if output != parsed.tokens().code() {
+                        if output != original {
                             code = 1;
                             writeln!(lock, "{}: check failed", name).unwrap();
                         } else {
                             writeln!(lock, "{}: check passed", name).unwrap();
                         }
                     } else {
-                        write_file(p, output);
+                        write_file(&path, output);
                     }
                 }
-            }
-            Err(e) => {
-                eprintln!("{}: error: {}", name, e);
-                code = 1;
-            }
+                Emit::Stdout => {
+                    write!(lock, "{}", output).unwrap();
+                }
+                Emit::Diff => {
+                    let d = diff::unified(&name, &original, &output);
+                    if !d.is_empty() {
+                        code = 1;
+                        write!(lock, "{}", d).unwrap();
+                    }
+                }
+                Emit::Json => {
+                    let m = diff::mismatches(&original, &output);
+                    if !m.is_empty() {
+                        code = 1;
+                        json_entries.push((name, m));
+                    }
+                }
+            },
         }
-    });
+    }
+    if emit == Emit::Json {
+        writeln!(lock, "{}", diff::mismatches_to_json(&json_entries)).unwrap();
+    }
     std::process::exit(code);
 }
 
@@ -172,3 +413,18 @@ fn read_file(from: &Path) -> Result<String, String> {
 fn write_file(to: &Path, code: String) {
     fs::write(to, code).unwrap_or_else(|_| panic!("{}: error writing a file", to.display()));
 }
+
+/// Render `formatted` using the line ending implied by `config.newline_style` and `original`.
+fn apply_newline_style(formatted: &str, original: &str, config: &Config) -> String {
+    let eol = config
+        .newline_style
+        .unwrap_or_default()
+        .line_ending(original);
+    if eol == "\n" {
+        format!("{}\n", formatted)
+    } else {
+        let mut out = formatted.replace('\n', eol);
+        out.push_str(eol);
+        out
+    }
+}